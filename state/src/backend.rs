@@ -0,0 +1,314 @@
+//! Storage backends for [`RawStoreHandle`](crate::raw::RawStoreHandle).
+//!
+//! [`RawStoreHandle`](crate::raw::RawStoreHandle) buffers drafted properties in a
+//! [`PropertyBackend`] before they are committed. [`StdBackend`] is the default: it buffers an
+//! unbounded number of properties in a `std::collections::HashMap`, same as before this module
+//! existed. [`HeaplessBackend`] buffers a fixed, caller-chosen number of properties inline
+//! instead, so drafting never touches a global allocator.
+//!
+//! Committing still has to linearize the drafted atom through
+//! [`SpaceElement::to_vec`](atom::space::SpaceElement::to_vec), which is the only extraction
+//! primitive the `atom` crate exposes and always returns a freshly allocated `Vec`; that single
+//! allocation per commit isn't something this module can remove without a no-alloc write API
+//! upstream in `atom`. What [`ScratchBuffer`] and [`PropertyBackend::Scratch`] do control is the
+//! buffer that result is copied into and that's actually handed to the host: instead of a fresh
+//! `Vec` dropped at the end of every `commit_pair` call, it's one buffer reused across commits,
+//! fixed-capacity and allocator-free for [`HeaplessBackend`].
+use crate::raw::StateFlags;
+use crate::StateErr;
+use atom::space::SpaceElement;
+use std::marker::PhantomData;
+use urid::URID;
+
+/// A reusable byte buffer that `RawStoreHandle::commit_pair` copies a linearized atom into
+/// before handing it to the host. The linearization itself (`SpaceElement::to_vec`) still
+/// allocates a fresh `Vec` on every commit; see the module documentation.
+///
+/// [`Vec<u8>`]'s impl (used by [`StdBackend`]) never fails and grows as needed.
+/// `heapless::Vec<u8, SCRATCH>`'s impl (used by [`HeaplessBackend`]) is fixed-capacity and
+/// reports [`StateErr::OutOfSpace`] instead of growing.
+pub trait ScratchBuffer {
+    /// Empty the buffer, keeping its capacity.
+    fn clear(&mut self);
+
+    /// Append `data`, failing instead of growing past a fixed-capacity buffer's limit.
+    fn extend_from_slice(&mut self, data: &[u8]) -> Result<(), StateErr>;
+
+    /// The buffer's current contents.
+    fn as_slice(&self) -> &[u8];
+}
+
+impl ScratchBuffer for Vec<u8> {
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+
+    fn extend_from_slice(&mut self, data: &[u8]) -> Result<(), StateErr> {
+        Vec::extend_from_slice(self, data);
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+impl<const SCRATCH: usize> ScratchBuffer for heapless::Vec<u8, SCRATCH> {
+    fn clear(&mut self) {
+        heapless::Vec::clear(self)
+    }
+
+    fn extend_from_slice(&mut self, data: &[u8]) -> Result<(), StateErr> {
+        heapless::Vec::extend_from_slice(self, data).map_err(|_| StateErr::OutOfSpace)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        heapless::Vec::as_slice(self)
+    }
+}
+
+/// A storage backend for drafted state properties.
+///
+/// This is the extension point that lets [`RawStoreHandle`](crate::raw::RawStoreHandle) be
+/// parameterized over how (and whether) it allocates. See [`StdBackend`] and
+/// [`HeaplessBackend`].
+pub trait PropertyBackend: Default {
+    /// The reusable commit-time buffer `RawStoreHandle` linearizes a drafted atom into; see the
+    /// module documentation.
+    type Scratch: ScratchBuffer + Default;
+
+    /// (Re-)start a draft for `key` with the given flags, returning the space to write the atom
+    /// into. A pre-existing, uncommitted draft for `key` is discarded.
+    fn draft(&mut self, key: URID, flags: StateFlags) -> Result<&mut SpaceElement, StateErr>;
+
+    /// Remove and return the draft for `key`, if any.
+    fn take(&mut self, key: URID) -> Option<(StateFlags, SpaceElement)>;
+
+    /// Remove every draft whose key matches `pred`, calling `f` with each one.
+    ///
+    /// Stops and propagates the error as soon as `f` returns one; drafts not yet visited are
+    /// left in place.
+    fn drain_matching(
+        &mut self,
+        pred: &mut dyn FnMut(URID) -> bool,
+        f: &mut dyn FnMut(URID, StateFlags, SpaceElement) -> Result<(), StateErr>,
+    ) -> Result<(), StateErr>;
+
+    /// Discard every pending draft.
+    fn clear(&mut self);
+
+    /// Discard the draft for `key`, if any.
+    fn discard(&mut self, key: URID);
+}
+
+/// The default [`PropertyBackend`], buffering drafted properties in a
+/// `std::collections::HashMap`. Equivalent to how `RawStoreHandle` behaved before backends were
+/// introduced: draughting and committing never fail due to capacity.
+#[derive(Default)]
+pub struct StdBackend(std::collections::HashMap<URID, (StateFlags, SpaceElement)>);
+
+impl PropertyBackend for StdBackend {
+    type Scratch = Vec<u8>;
+
+    fn draft(&mut self, key: URID, flags: StateFlags) -> Result<&mut SpaceElement, StateErr> {
+        self.0.insert(key, (flags, SpaceElement::default()));
+        Ok(&mut self.0.get_mut(&key).unwrap().1)
+    }
+
+    fn take(&mut self, key: URID) -> Option<(StateFlags, SpaceElement)> {
+        self.0.remove(&key)
+    }
+
+    fn drain_matching(
+        &mut self,
+        pred: &mut dyn FnMut(URID) -> bool,
+        f: &mut dyn FnMut(URID, StateFlags, SpaceElement) -> Result<(), StateErr>,
+    ) -> Result<(), StateErr> {
+        let matching: Vec<URID> = self.0.keys().copied().filter(|key| pred(*key)).collect();
+        for key in matching {
+            let (flags, space) = self.0.remove(&key).unwrap();
+            f(key, flags, space)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    fn discard(&mut self, key: URID) {
+        self.0.remove(&key);
+    }
+}
+
+/// A fixed-capacity [`PropertyBackend`] that buffers at most `N` drafted properties inline,
+/// using a [`heapless::Vec`] instead of a `HashMap`. Drafting a property beyond the `N`th
+/// distinct, uncommitted key fails with [`StateErr::OutOfSpace`] instead of growing, so drafting
+/// needs no global allocator and is suitable for `no_std` targets.
+///
+/// Committing still goes through `SpaceElement::to_vec`'s allocation (see the module
+/// documentation), but the buffer that result is copied into and handed to the host is this
+/// backend's fixed `SCRATCH`-byte `heapless::Vec`: a property that doesn't fit also fails with
+/// [`StateErr::OutOfSpace`] instead of growing.
+pub struct HeaplessBackend<const N: usize, const SCRATCH: usize = 256>(
+    heapless::Vec<(URID, StateFlags, SpaceElement), N>,
+    PhantomData<[u8; SCRATCH]>,
+);
+
+impl<const N: usize, const SCRATCH: usize> Default for HeaplessBackend<N, SCRATCH> {
+    fn default() -> Self {
+        HeaplessBackend(heapless::Vec::new(), PhantomData)
+    }
+}
+
+impl<const N: usize, const SCRATCH: usize> HeaplessBackend<N, SCRATCH> {
+    fn position(&self, key: URID) -> Option<usize> {
+        self.0.iter().position(|(k, _, _)| *k == key)
+    }
+}
+
+impl<const N: usize, const SCRATCH: usize> PropertyBackend for HeaplessBackend<N, SCRATCH> {
+    type Scratch = heapless::Vec<u8, SCRATCH>;
+
+    fn draft(&mut self, key: URID, flags: StateFlags) -> Result<&mut SpaceElement, StateErr> {
+        if let Some(index) = self.position(key) {
+            self.0[index] = (key, flags, SpaceElement::default());
+        } else {
+            self.0
+                .push((key, flags, SpaceElement::default()))
+                .map_err(|_| StateErr::OutOfSpace)?;
+        }
+        let index = self.position(key).unwrap();
+        Ok(&mut self.0[index].2)
+    }
+
+    fn take(&mut self, key: URID) -> Option<(StateFlags, SpaceElement)> {
+        let index = self.position(key)?;
+        let (_, flags, space) = self.0.swap_remove(index);
+        Some((flags, space))
+    }
+
+    fn drain_matching(
+        &mut self,
+        pred: &mut dyn FnMut(URID) -> bool,
+        f: &mut dyn FnMut(URID, StateFlags, SpaceElement) -> Result<(), StateErr>,
+    ) -> Result<(), StateErr> {
+        let mut matching: heapless::Vec<URID, N> = heapless::Vec::new();
+        for (key, _, _) in self.0.iter() {
+            if pred(*key) {
+                // `matching` can hold at most as many keys as `self.0`, so this never fails.
+                let _ = matching.push(*key);
+            }
+        }
+
+        for key in matching {
+            let (flags, space) = self.take(key).unwrap();
+            f(key, flags, space)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    fn discard(&mut self, key: URID) {
+        if let Some(index) = self.position(key) {
+            self.0.swap_remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urid(n: u32) -> URID {
+        URID::new(n).unwrap()
+    }
+
+    #[test]
+    fn heapless_backend_out_of_space() {
+        let mut backend = HeaplessBackend::<2>::default();
+        backend.draft(urid(1), StateFlags::default()).unwrap();
+        backend.draft(urid(2), StateFlags::default()).unwrap();
+
+        assert!(matches!(
+            backend.draft(urid(3), StateFlags::default()),
+            Err(StateErr::OutOfSpace)
+        ));
+    }
+
+    #[test]
+    fn heapless_backend_redrafts_existing_key_in_place() {
+        let mut backend = HeaplessBackend::<2>::default();
+        backend.draft(urid(1), StateFlags::POD).unwrap();
+        backend.draft(urid(1), StateFlags::PORTABLE).unwrap();
+        backend.draft(urid(2), StateFlags::default()).unwrap();
+
+        // Redrafting `1` must not have consumed a second slot.
+        assert!(matches!(
+            backend.draft(urid(3), StateFlags::default()),
+            Err(StateErr::OutOfSpace)
+        ));
+        let (flags, _) = backend.take(urid(1)).unwrap();
+        assert_eq!(flags, StateFlags::PORTABLE);
+    }
+
+    #[test]
+    fn heapless_backend_take_and_discard() {
+        let mut backend = HeaplessBackend::<3>::default();
+        backend.draft(urid(1), StateFlags::default()).unwrap();
+        backend.draft(urid(2), StateFlags::default()).unwrap();
+        backend.draft(urid(3), StateFlags::default()).unwrap();
+
+        backend.discard(urid(2));
+        assert!(backend.take(urid(2)).is_none());
+        // `discard`/`take` are implemented via `swap_remove`; make sure the other two slots
+        // (one of which was moved by the swap) are still both retrievable exactly once.
+        assert!(backend.take(urid(1)).is_some());
+        assert!(backend.take(urid(3)).is_some());
+        assert!(backend.take(urid(3)).is_none());
+    }
+
+    #[test]
+    fn heapless_backend_drain_matching_stops_on_error() {
+        let mut backend = HeaplessBackend::<4>::default();
+        backend.draft(urid(1), StateFlags::default()).unwrap();
+        backend.draft(urid(2), StateFlags::default()).unwrap();
+        backend.draft(urid(3), StateFlags::default()).unwrap();
+
+        let mut committed = Vec::new();
+        let result = backend.drain_matching(&mut |_| true, &mut |key, _, _| {
+            if key == urid(2) {
+                return Err(StateErr::BadData);
+            }
+            committed.push(key);
+            Ok(())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(committed, vec![urid(1)]);
+        // `3` was never visited, so it must still be buffered.
+        assert!(backend.take(urid(3)).is_some());
+    }
+
+    #[test]
+    fn heapless_scratch_buffer_reuses_capacity_and_reports_out_of_space() {
+        let mut scratch = <HeaplessBackend<1, 4> as PropertyBackend>::Scratch::default();
+
+        ScratchBuffer::extend_from_slice(&mut scratch, &[1, 2]).unwrap();
+        assert_eq!(scratch.as_slice(), &[1, 2]);
+
+        // Reusing the buffer for a second, smaller commit must not leave stale bytes behind.
+        ScratchBuffer::clear(&mut scratch);
+        ScratchBuffer::extend_from_slice(&mut scratch, &[3]).unwrap();
+        assert_eq!(scratch.as_slice(), &[3]);
+
+        ScratchBuffer::clear(&mut scratch);
+        assert!(matches!(
+            ScratchBuffer::extend_from_slice(&mut scratch, &[0, 0, 0, 0, 0]),
+            Err(StateErr::OutOfSpace)
+        ));
+    }
+}
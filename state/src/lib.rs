@@ -0,0 +1,33 @@
+//! Implementation of the [LV2 State extension](http://lv2plug.in/ns/ext/state/state.html).
+pub mod backend;
+pub mod interface;
+pub mod raw;
+pub mod storage;
+
+/// Errors that can occur while saving or restoring LV2 state.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StateErr {
+    /// A callback the host is required to provide (a store/retrieve function, or a make-path/
+    /// map-path/free-path function) was null.
+    BadCallback,
+    /// Data read from or written to the host was malformed: not valid UTF-8, containing an
+    /// interior nul byte, too short to contain the header it claims to, or otherwise not what was
+    /// expected. Also returned when the host's store function reports any non-success status, or
+    /// when a host path function returns a null path.
+    BadData,
+    /// A fixed-capacity backend had no room left for another draft, or for another byte of
+    /// commit-time scratch space.
+    OutOfSpace,
+}
+
+impl StateErr {
+    /// Convert a raw `LV2_State_Status` returned by a host store function into a result, treating
+    /// every non-success status as [`StateErr::BadData`].
+    pub(crate) fn from(status: sys::LV2_State_Status) -> Result<(), Self> {
+        if status == sys::LV2_State_Status_LV2_STATE_SUCCESS {
+            Ok(())
+        } else {
+            Err(StateErr::BadData)
+        }
+    }
+}
@@ -1,46 +1,113 @@
+use crate::backend::{PropertyBackend, ScratchBuffer, StdBackend};
 use crate::interface::*;
 use crate::StateErr;
 use atom::prelude::*;
 use atom::space::*;
-use std::collections::HashMap;
-use std::ffi::c_void;
+use lv2_core::feature::{Feature, ThreadingClass};
+use std::ffi::{c_void, CStr, CString};
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 use urid::prelude::*;
 
+bitflags::bitflags! {
+    /// Flags describing how a stored state property should be saved.
+    ///
+    /// These correspond to the `LV2_STATE_IS_POD` and `LV2_STATE_IS_PORTABLE` flags of the LV2
+    /// State extension. Data that contains pointers, offsets or other references the host must
+    /// not blindly copy has to drop [`POD`](Self::POD), and data that is specific to this
+    /// machine or plugin instance (a file descriptor, an absolute path, a platform-dependent
+    /// layout) has to drop [`PORTABLE`](Self::PORTABLE).
+    pub struct StateFlags: u32 {
+        /// The property's data is plain old data: it contains no pointers or references and can
+        /// be safely copied with `memcpy`.
+        const POD = sys::LV2_State_Flags_LV2_STATE_IS_POD;
+        /// The property's data is portable: it is safe to copy to another machine or program.
+        const PORTABLE = sys::LV2_State_Flags_LV2_STATE_IS_PORTABLE;
+    }
+}
+
+impl Default for StateFlags {
+    /// The flags used by [`StoreHandle::draft`](StoreHandle::draft): `POD | PORTABLE`.
+    fn default() -> Self {
+        StateFlags::POD | StateFlags::PORTABLE
+    }
+}
+
 /// A handle to abstract state storage.
 ///
 /// This handle buffers the written properties and flushes them at once. Create new properties by calling [`draft`](#method.draft) and write them like any other atom. Once you are done, you can commit your properties by calling [`commit_all`](#method.commit_all) or [`commit`](#method.commit). You have to commit manually: Uncommitted properties will be discarded when the handle is dropped.
-pub struct RawStoreHandle<'a> {
-    properties: HashMap<URID, SpaceElement>,
+///
+/// Buffering is generic over a [`PropertyBackend`]. The default, [`StdBackend`], never runs out
+/// of room to draft or commit a property; a fixed-capacity [`HeaplessBackend`](crate::backend::HeaplessBackend)
+/// is also available for `no_std`/fallible-allocation targets, making *drafting* allocation-free,
+/// at the cost of [`draft_with_flags`](#method.draft_with_flags) and
+/// [`commit_matching`](#method.commit_matching) being fallible. Committing still allocates once
+/// per call regardless of backend; see below.
+///
+/// Each handle also owns one reusable [`ScratchBuffer`] (its backend's
+/// [`PropertyBackend::Scratch`]), which `commit_pair` copies a linearized atom into and hands to
+/// the host, instead of handing over a fresh buffer on every commit; see the [`backend`](crate::backend) module
+/// documentation for what this does and doesn't buy on `no_std` targets — committing a drafted
+/// atom always allocates once, in `SpaceElement::to_vec`, no matter which backend is used.
+pub struct RawStoreHandle<'a, B: PropertyBackend = StdBackend> {
+    properties: B,
+    scratch: B::Scratch,
     store_fn: sys::LV2_State_Store_Function,
     handle: sys::LV2_State_Handle,
     lifetime: PhantomData<&'a mut c_void>,
 }
 
-impl<'a> RawStoreHandle<'a> {
+impl<'a, B: PropertyBackend> RawStoreHandle<'a, B> {
     /// Create a new store handle.
     pub unsafe fn new(
         store_fn: sys::LV2_State_Store_Function,
         handle: sys::LV2_State_Handle,
     ) -> Self {
         RawStoreHandle {
-            properties: HashMap::new(),
+            properties: B::default(),
+            scratch: B::Scratch::default(),
             store_fn,
             handle,
             lifetime: PhantomData,
         }
     }
 
+    /// Draft a new property, storing it with flags other than the default `POD | PORTABLE`.
+    ///
+    /// This is useful for handle-specific or machine-specific data (file descriptors, absolute
+    /// paths) that should be saved without [`StateFlags::PORTABLE`], or for data containing
+    /// pointers or offsets the host must not `memcpy`, which should be saved without
+    /// [`StateFlags::POD`].
+    ///
+    /// Fails with `StateErr::OutOfSpace` if the backend is at capacity and `property_key` is not
+    /// already drafted.
+    pub fn draft_with_flags(
+        &mut self,
+        property_key: URID,
+        flags: StateFlags,
+    ) -> Result<StatePropertyWriter, StateErr> {
+        let space = self.properties.draft(property_key, flags)?;
+        Ok(StatePropertyWriter::new(SpaceHead::new(space)))
+    }
+
     /// Internal helper function to store one property.
-    fn commit_pair(
+    ///
+    /// The atom is first linearized through [`SpaceElement::to_vec`], which always allocates a
+    /// fresh `Vec` (see the [`backend`](crate::backend) module documentation), then copied into
+    /// `scratch`, which `RawStoreHandle` reuses across commits instead of allocating a fresh
+    /// buffer for the bytes actually handed to the host.
+    fn commit_pair<S: ScratchBuffer>(
         store_fn: sys::LV2_State_Store_Function,
         handle: sys::LV2_State_Handle,
         key: URID,
+        flags: StateFlags,
         space: SpaceElement,
+        scratch: &mut S,
     ) -> Result<(), StateErr> {
         let store_fn = store_fn.ok_or(StateErr::BadCallback)?;
-        let space: Vec<u8> = space.to_vec();
-        let space = Space::from_slice(space.as_ref());
+        scratch.clear();
+        scratch.extend_from_slice(&space.to_vec())?;
+        let space = Space::from_slice(scratch.as_slice());
         let (header, data) = space
             .split_type::<sys::LV2_Atom>()
             .ok_or(StateErr::BadData)?;
@@ -53,39 +120,67 @@ impl<'a> RawStoreHandle<'a> {
         let data_ptr = data as *const _ as *const c_void;
         let data_size = header.size as usize;
         let data_type = header.type_;
-        let flags =
-            sys::LV2_State_Flags_LV2_STATE_IS_POD | sys::LV2_State_Flags_LV2_STATE_IS_PORTABLE;
+        let flags = flags.bits();
         StateErr::from(unsafe { (store_fn)(handle, key, data_ptr, data_size, data_type, flags) })
     }
+
+    /// Commit every drafted property whose key matches `pred`, leaving the rest buffered.
+    ///
+    /// This is useful to flush a subset of drafted properties in one call (e.g. all properties
+    /// of a parameter group) while keeping other, still-transient drafts pending.
+    ///
+    /// Matching properties are committed one at a time. The first failing commit stops the call
+    /// and its error is returned: properties committed before it stay committed, the failing one
+    /// is dropped, and matching properties not yet reached stay buffered.
+    pub fn commit_matching(&mut self, mut pred: impl FnMut(URID) -> bool) -> Result<(), StateErr> {
+        let store_fn = self.store_fn;
+        let handle = self.handle;
+        let scratch = &mut self.scratch;
+        self.properties
+            .drain_matching(&mut pred, &mut |key, flags, space| {
+                Self::commit_pair(store_fn, handle, key, flags, space, scratch)
+            })
+    }
+
+    /// Discard every pending draft.
+    pub fn discard_all(&mut self) {
+        self.properties.clear();
+    }
+
+    /// Discard the draft for `key`, if any.
+    pub fn discard(&mut self, key: URID) {
+        self.properties.discard(key);
+    }
 }
 
-impl<'a> StoreHandle for RawStoreHandle<'a> {
+impl<'a> StoreHandle for RawStoreHandle<'a, StdBackend> {
     fn draft(&mut self, property_key: URID) -> StatePropertyWriter {
-        self.properties
-            .insert(property_key, SpaceElement::default());
-        StatePropertyWriter::new(SpaceHead::new(
-            self.properties.get_mut(&property_key).unwrap(),
-        ))
+        self.draft_with_flags(property_key, StateFlags::default())
+            .expect("StdBackend never fails to draft a property")
     }
 
     fn commit_all(&mut self) -> Result<(), StateErr> {
-        for (key, space) in self.properties.drain() {
-            Self::commit_pair(self.store_fn, self.handle, key, space)?;
-        }
-        Ok(())
+        self.commit_matching(|_| true)
     }
 
     fn commit(&mut self, key: URID) -> Option<Result<(), StateErr>> {
-        let space = self.properties.remove(&key)?;
-        Some(Self::commit_pair(self.store_fn, self.handle, key, space))
+        let (flags, space) = self.properties.take(key)?;
+        Some(Self::commit_pair(
+            self.store_fn,
+            self.handle,
+            key,
+            flags,
+            space,
+            &mut self.scratch,
+        ))
     }
 
     fn discard_all(&mut self) {
-        self.properties.clear();
+        RawStoreHandle::discard_all(self)
     }
 
     fn discard(&mut self, key: URID) {
-        self.properties.remove(&key);
+        RawStoreHandle::discard(self, key)
     }
 }
 
@@ -133,11 +228,222 @@ impl<'a> RetrieveHandle for RawRetrieveHandle<'a> {
     }
 }
 
+/// The host's `LV2_State_Free_Path` feature, required alongside [`MakePathFeature`] or
+/// [`MapPathFeature`] to free the host-owned C strings either of them return.
+pub struct FreePathFeature<'a> {
+    internal: *const sys::LV2_State_Free_Path,
+    lifetime: PhantomData<&'a mut c_void>,
+}
+
+unsafe impl<'a> Feature for FreePathFeature<'a> {
+    const URI: &'static [u8] = sys::LV2_STATE__freePath;
+
+    unsafe fn from_feature_ptr(feature: *const c_void, _class: ThreadingClass) -> Option<Self> {
+        if feature.is_null() {
+            return None;
+        }
+        Some(FreePathFeature {
+            internal: feature as *const sys::LV2_State_Free_Path,
+            lifetime: PhantomData,
+        })
+    }
+}
+
+/// The host's `LV2_State_Make_Path` feature, retrievable from the plugin's feature collection at
+/// instantiation. Combine with a [`FreePathFeature`] via [`MakePathHandle::from_features`] to get
+/// a handle usable inside `State::save`/`State::restore`.
+pub struct MakePathFeature<'a> {
+    internal: *const sys::LV2_State_Make_Path,
+    lifetime: PhantomData<&'a mut c_void>,
+}
+
+unsafe impl<'a> Feature for MakePathFeature<'a> {
+    const URI: &'static [u8] = sys::LV2_STATE__makePath;
+
+    unsafe fn from_feature_ptr(feature: *const c_void, _class: ThreadingClass) -> Option<Self> {
+        if feature.is_null() {
+            return None;
+        }
+        Some(MakePathFeature {
+            internal: feature as *const sys::LV2_State_Make_Path,
+            lifetime: PhantomData,
+        })
+    }
+}
+
+/// A handle to the host's `LV2_State_Make_Path` feature.
+///
+/// Plugins that need to persist bulk data alongside their POD state (recorded audio, sample
+/// files, impulse responses) can use this handle to ask the host for a real, writable path on
+/// disk corresponding to some plugin-chosen abstract path. The host may relocate the returned
+/// path later (e.g. when moving a project between machines), so the abstract path, not the
+/// absolute one, is what should be written into the saved state.
+///
+/// A host that provides `LV2_State_Make_Path` always provides `LV2_State_Free_Path` too, so both
+/// are required to construct this handle.
+pub struct MakePathHandle<'a> {
+    internal: *const sys::LV2_State_Make_Path,
+    free_path: *const sys::LV2_State_Free_Path,
+    lifetime: PhantomData<&'a mut c_void>,
+}
+
+impl<'a> MakePathHandle<'a> {
+    /// Create a new make path handle.
+    pub unsafe fn new(
+        internal: *const sys::LV2_State_Make_Path,
+        free_path: *const sys::LV2_State_Free_Path,
+    ) -> Self {
+        MakePathHandle {
+            internal,
+            free_path,
+            lifetime: PhantomData,
+        }
+    }
+
+    /// Build a handle from the make-path and free-path features resolved from the plugin's
+    /// feature collection at instantiation.
+    pub fn from_features(make_path: &MakePathFeature<'a>, free_path: &FreePathFeature<'a>) -> Self {
+        unsafe { Self::new(make_path.internal, free_path.internal) }
+    }
+
+    /// Ask the host for an absolute, writable path for the given abstract path.
+    ///
+    /// The returned path is safe to create and write to immediately, even before the plugin's
+    /// state has been saved. The host-owned C string backing the returned path is freed before
+    /// this method returns.
+    pub fn absolute_path(&self, r#abstract: &str) -> Result<PathBuf, StateErr> {
+        let internal = unsafe { self.internal.as_ref() }.ok_or(StateErr::BadCallback)?;
+        let path_fn = internal.path.ok_or(StateErr::BadCallback)?;
+        let free_path = unsafe { self.free_path.as_ref() }.ok_or(StateErr::BadCallback)?;
+        let free_fn = free_path.free_path.ok_or(StateErr::BadCallback)?;
+
+        let r#abstract = CString::new(r#abstract).map_err(|_| StateErr::BadData)?;
+        let raw_path = unsafe { (path_fn)(internal.handle, r#abstract.as_ptr()) };
+        if raw_path.is_null() {
+            return Err(StateErr::BadData);
+        }
+
+        let owned_path = unsafe { CStr::from_ptr(raw_path) }
+            .to_str()
+            .map(str::to_owned);
+        unsafe { (free_fn)(free_path.handle, raw_path) };
+
+        Ok(PathBuf::from(owned_path.map_err(|_| StateErr::BadData)?))
+    }
+}
+
+/// The host's `LV2_State_Map_Path` feature, retrievable from the plugin's feature collection at
+/// instantiation. Combine with a [`FreePathFeature`] via [`MapPathHandle::from_features`] to get
+/// a handle usable inside `State::save`/`State::restore`.
+pub struct MapPathFeature<'a> {
+    internal: *const sys::LV2_State_Map_Path,
+    lifetime: PhantomData<&'a mut c_void>,
+}
+
+unsafe impl<'a> Feature for MapPathFeature<'a> {
+    const URI: &'static [u8] = sys::LV2_STATE__mapPath;
+
+    unsafe fn from_feature_ptr(feature: *const c_void, _class: ThreadingClass) -> Option<Self> {
+        if feature.is_null() {
+            return None;
+        }
+        Some(MapPathFeature {
+            internal: feature as *const sys::LV2_State_Map_Path,
+            lifetime: PhantomData,
+        })
+    }
+}
+
+/// A handle to the host's `LV2_State_Map_Path` feature.
+///
+/// This handle translates between absolute, on-disk paths and the host-relative "abstract" paths
+/// that are safe to write into saved state: a plugin stores the abstract form of a path property
+/// with [`abstract_path`](#method.abstract_path) and reconstructs the real, absolute path on
+/// restore with [`absolute_path`](#method.absolute_path).
+///
+/// A host that provides `LV2_State_Map_Path` always provides `LV2_State_Free_Path` too, so both
+/// are required to construct this handle.
+pub struct MapPathHandle<'a> {
+    internal: *const sys::LV2_State_Map_Path,
+    free_path: *const sys::LV2_State_Free_Path,
+    lifetime: PhantomData<&'a mut c_void>,
+}
+
+impl<'a> MapPathHandle<'a> {
+    /// Create a new map path handle.
+    pub unsafe fn new(
+        internal: *const sys::LV2_State_Map_Path,
+        free_path: *const sys::LV2_State_Free_Path,
+    ) -> Self {
+        MapPathHandle {
+            internal,
+            free_path,
+            lifetime: PhantomData,
+        }
+    }
+
+    /// Build a handle from the map-path and free-path features resolved from the plugin's
+    /// feature collection at instantiation.
+    pub fn from_features(map_path: &MapPathFeature<'a>, free_path: &FreePathFeature<'a>) -> Self {
+        unsafe { Self::new(map_path.internal, free_path.internal) }
+    }
+
+    /// Translate the host-owned C string returned by `map_fn` into an owned Rust string, freeing
+    /// it via the free path feature.
+    fn map(
+        &self,
+        map_fn: unsafe extern "C" fn(
+            sys::LV2_State_Map_Path_Handle,
+            *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char,
+        handle: sys::LV2_State_Map_Path_Handle,
+        input: &CStr,
+    ) -> Result<String, StateErr> {
+        let free_path = unsafe { self.free_path.as_ref() }.ok_or(StateErr::BadCallback)?;
+        let free_fn = free_path.free_path.ok_or(StateErr::BadCallback)?;
+
+        let raw_path = unsafe { (map_fn)(handle, input.as_ptr()) };
+        if raw_path.is_null() {
+            return Err(StateErr::BadData);
+        }
+
+        let owned = unsafe { CStr::from_ptr(raw_path) }
+            .to_str()
+            .map(str::to_owned);
+        unsafe { (free_fn)(free_path.handle, raw_path) };
+
+        owned.map_err(|_| StateErr::BadData)
+    }
+
+    /// Convert an absolute, on-disk path into its host-relative abstract form, suitable for
+    /// writing into saved state.
+    pub fn abstract_path(&self, absolute: &Path) -> Result<String, StateErr> {
+        let internal = unsafe { self.internal.as_ref() }.ok_or(StateErr::BadCallback)?;
+        let map_fn = internal.abstract_path.ok_or(StateErr::BadCallback)?;
+        let absolute = absolute.to_str().ok_or(StateErr::BadData)?;
+        let absolute = CString::new(absolute).map_err(|_| StateErr::BadData)?;
+        self.map(map_fn, internal.handle, &absolute)
+    }
+
+    /// Reconstruct the real, absolute path corresponding to an abstract path previously written
+    /// into saved state.
+    pub fn absolute_path(&self, r#abstract: &str) -> Result<PathBuf, StateErr> {
+        let internal = unsafe { self.internal.as_ref() }.ok_or(StateErr::BadCallback)?;
+        let map_fn = internal.absolute_path.ok_or(StateErr::BadCallback)?;
+        let r#abstract = CString::new(r#abstract).map_err(|_| StateErr::BadData)?;
+        self.map(map_fn, internal.handle, &r#abstract).map(PathBuf::from)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::backend::HeaplessBackend;
     use crate::raw::*;
     use crate::storage::Storage;
     use atom::space::Space;
+    use std::ffi::{CStr, CString};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use urid::mapper::*;
 
     fn store(storage: &mut Storage, urids: &AtomURIDCache) {
@@ -231,4 +537,439 @@ mod tests {
 
         retrieve(&mut storage, &urids);
     }
+
+    #[test]
+    fn test_commit_matching_leaves_unmatched_buffered() {
+        let mut mapper = Box::pin(HashURIDMapper::new());
+        let interface = mapper.as_mut().make_map_interface();
+        let map = Map::new(&interface);
+        let urids = AtomURIDCache::from_map(&map).unwrap();
+
+        let mut storage = Storage::default();
+        let mut store_handle = storage.store_handle();
+
+        store_handle
+            .draft(URID::new(1).unwrap())
+            .init(urids.int, 1)
+            .unwrap();
+        store_handle
+            .draft(URID::new(2).unwrap())
+            .init(urids.int, 2)
+            .unwrap();
+        store_handle
+            .draft(URID::new(3).unwrap())
+            .init(urids.int, 3)
+            .unwrap();
+
+        // Key 2 doesn't match, so it must stay buffered: committing it explicitly afterwards
+        // must still succeed.
+        store_handle.commit_matching(|key| key.get() != 2).unwrap();
+        assert!(store_handle.commit(URID::new(2).unwrap()).unwrap().is_ok());
+        drop(store_handle);
+
+        let retrieve_handle = storage.retrieve_handle();
+        for (key, expected) in [(1, 1), (2, 2), (3, 3)] {
+            assert_eq!(
+                expected,
+                retrieve_handle
+                    .retrieve(URID::new(key).unwrap())
+                    .unwrap()
+                    .read(urids.int, ())
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_commit_matching_stops_on_error_and_leaves_unmatched_buffered() {
+        struct FailingStore {
+            committed: Vec<u32>,
+        }
+
+        unsafe extern "C" fn failing_store_fn(
+            handle: sys::LV2_State_Handle,
+            key: u32,
+            _value: *const c_void,
+            _size: usize,
+            _type_: u32,
+            _flags: u32,
+        ) -> sys::LV2_State_Status {
+            let state = &mut *(handle as *mut FailingStore);
+            if key == 2 {
+                return sys::LV2_State_Status_LV2_STATE_ERR_UNKNOWN;
+            }
+            state.committed.push(key);
+            sys::LV2_State_Status_LV2_STATE_SUCCESS
+        }
+
+        let mut mapper = Box::pin(HashURIDMapper::new());
+        let interface = mapper.as_mut().make_map_interface();
+        let map = Map::new(&interface);
+        let urids = AtomURIDCache::from_map(&map).unwrap();
+
+        let mut state = FailingStore {
+            committed: Vec::new(),
+        };
+        let mut store_handle = unsafe {
+            RawStoreHandle::new(
+                Some(failing_store_fn),
+                &mut state as *mut FailingStore as *mut c_void,
+            )
+        };
+
+        store_handle
+            .draft(URID::new(1).unwrap())
+            .init(urids.int, 1)
+            .unwrap();
+        store_handle
+            .draft(URID::new(2).unwrap())
+            .init(urids.int, 2)
+            .unwrap();
+
+        // Only key 2 matches, and its commit fails: the error must propagate, and key 1 (never
+        // matched at all) must be untouched.
+        assert!(store_handle.commit_matching(|key| key.get() == 2).is_err());
+        assert!(state.committed.is_empty());
+
+        assert!(store_handle.commit(URID::new(1).unwrap()).unwrap().is_ok());
+        assert_eq!(vec![1], state.committed);
+    }
+
+    /// Exercises the path a `no_std` plugin would take to drive `State::save`'s drafting without
+    /// a global allocator: a `RawStoreHandle` backed by a fixed-capacity `HeaplessBackend` instead
+    /// of the default `StdBackend`, going through the same host store/retrieve functions as
+    /// [`test_storage`]. Committing still allocates once per property via `SpaceElement::to_vec`
+    /// regardless of backend; see the [`backend`](crate::backend) module documentation.
+    #[test]
+    fn test_heapless_backend_store_handle() {
+        let mut mapper = Box::pin(HashURIDMapper::new());
+        let interface = mapper.as_mut().make_map_interface();
+        let map = Map::new(&interface);
+        let urids = AtomURIDCache::from_map(&map).unwrap();
+
+        let mut storage = Storage::default();
+        let mut store_handle: RawStoreHandle<HeaplessBackend<2>> = storage.store_handle_with();
+
+        store_handle
+            .draft_with_flags(URID::new(1).unwrap(), StateFlags::default())
+            .unwrap()
+            .init(urids.int, 17)
+            .unwrap();
+        store_handle
+            .draft_with_flags(URID::new(2).unwrap(), StateFlags::POD)
+            .unwrap()
+            .init(urids.float, 1.0)
+            .unwrap();
+
+        assert!(matches!(
+            store_handle.draft_with_flags(URID::new(3).unwrap(), StateFlags::default()),
+            Err(crate::StateErr::OutOfSpace)
+        ));
+
+        store_handle.commit_matching(|_| true).unwrap();
+
+        let retrieve_handle = storage.retrieve_handle();
+        assert_eq!(
+            17,
+            retrieve_handle
+                .retrieve(URID::new(1).unwrap())
+                .unwrap()
+                .read(urids.int, ())
+                .unwrap()
+        );
+        assert_eq!(
+            1.0,
+            retrieve_handle
+                .retrieve(URID::new(2).unwrap())
+                .unwrap()
+                .read(urids.float, ())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_make_path_absolute_path_round_trips_and_frees() {
+        static FREE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "C" fn path_fn(
+            _handle: sys::LV2_State_Make_Path_Handle,
+            abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            let abstract_path = CStr::from_ptr(abstract_path).to_str().unwrap();
+            CString::new(format!("/real/{}", abstract_path)).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            path: *mut std::os::raw::c_char,
+        ) {
+            FREE_CALLS.fetch_add(1, Ordering::SeqCst);
+            drop(CString::from_raw(path));
+        }
+
+        let make_path = sys::LV2_State_Make_Path {
+            handle: std::ptr::null_mut(),
+            path: Some(path_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        let handle = unsafe { MakePathHandle::new(&make_path, &free_path) };
+        let path = handle.absolute_path("presets/a.wav").unwrap();
+
+        assert_eq!(PathBuf::from("/real/presets/a.wav"), path);
+        assert_eq!(1, FREE_CALLS.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_make_path_handle_from_resolved_features() {
+        unsafe extern "C" fn path_fn(
+            _handle: sys::LV2_State_Make_Path_Handle,
+            abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            let abstract_path = CStr::from_ptr(abstract_path).to_str().unwrap();
+            CString::new(format!("/real/{}", abstract_path)).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            path: *mut std::os::raw::c_char,
+        ) {
+            drop(CString::from_raw(path));
+        }
+
+        let make_path = sys::LV2_State_Make_Path {
+            handle: std::ptr::null_mut(),
+            path: Some(path_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        // Resolve both features the way a plugin's feature collection would, then combine them.
+        let make_path_feature = unsafe {
+            MakePathFeature::from_feature_ptr(
+                &make_path as *const _ as *const c_void,
+                ThreadingClass::Instantiation,
+            )
+        }
+        .unwrap();
+        let free_path_feature = unsafe {
+            FreePathFeature::from_feature_ptr(
+                &free_path as *const _ as *const c_void,
+                ThreadingClass::Instantiation,
+            )
+        }
+        .unwrap();
+
+        let handle = MakePathHandle::from_features(&make_path_feature, &free_path_feature);
+        assert_eq!(
+            PathBuf::from("/real/presets/a.wav"),
+            handle.absolute_path("presets/a.wav").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_make_path_null_path_is_bad_data() {
+        unsafe extern "C" fn path_fn(
+            _handle: sys::LV2_State_Make_Path_Handle,
+            _abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            std::ptr::null_mut()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            _path: *mut std::os::raw::c_char,
+        ) {
+            panic!("free_path must not be called when the host returns no path");
+        }
+
+        let make_path = sys::LV2_State_Make_Path {
+            handle: std::ptr::null_mut(),
+            path: Some(path_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        let handle = unsafe { MakePathHandle::new(&make_path, &free_path) };
+        assert!(matches!(handle.absolute_path("x"), Err(StateErr::BadData)));
+    }
+
+    #[test]
+    fn test_make_path_invalid_utf8_still_frees() {
+        static FREE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "C" fn path_fn(
+            _handle: sys::LV2_State_Make_Path_Handle,
+            _abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            CString::new(vec![0xFF, 0xFE]).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            path: *mut std::os::raw::c_char,
+        ) {
+            FREE_CALLS.fetch_add(1, Ordering::SeqCst);
+            drop(CString::from_raw(path));
+        }
+
+        let make_path = sys::LV2_State_Make_Path {
+            handle: std::ptr::null_mut(),
+            path: Some(path_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        let handle = unsafe { MakePathHandle::new(&make_path, &free_path) };
+        assert!(matches!(handle.absolute_path("x"), Err(StateErr::BadData)));
+        assert_eq!(1, FREE_CALLS.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_map_path_round_trips() {
+        unsafe extern "C" fn abstract_fn(
+            _handle: sys::LV2_State_Map_Path_Handle,
+            absolute_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            let absolute_path = CStr::from_ptr(absolute_path).to_str().unwrap();
+            let abstract_path = absolute_path.strip_prefix("/real/").unwrap();
+            CString::new(abstract_path).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn absolute_fn(
+            _handle: sys::LV2_State_Map_Path_Handle,
+            abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            let abstract_path = CStr::from_ptr(abstract_path).to_str().unwrap();
+            CString::new(format!("/real/{}", abstract_path)).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            path: *mut std::os::raw::c_char,
+        ) {
+            drop(CString::from_raw(path));
+        }
+
+        let map_path = sys::LV2_State_Map_Path {
+            handle: std::ptr::null_mut(),
+            abstract_path: Some(abstract_fn),
+            absolute_path: Some(absolute_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        let handle = unsafe { MapPathHandle::new(&map_path, &free_path) };
+
+        let abstract_path = handle
+            .abstract_path(Path::new("/real/presets/a.wav"))
+            .unwrap();
+        assert_eq!("presets/a.wav", abstract_path);
+
+        let absolute_path = handle.absolute_path(&abstract_path).unwrap();
+        assert_eq!(PathBuf::from("/real/presets/a.wav"), absolute_path);
+    }
+
+    #[test]
+    fn test_map_path_handle_from_resolved_features() {
+        unsafe extern "C" fn abstract_fn(
+            _handle: sys::LV2_State_Map_Path_Handle,
+            absolute_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            let absolute_path = CStr::from_ptr(absolute_path).to_str().unwrap();
+            let abstract_path = absolute_path.strip_prefix("/real/").unwrap();
+            CString::new(abstract_path).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn absolute_fn(
+            _handle: sys::LV2_State_Map_Path_Handle,
+            abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            let abstract_path = CStr::from_ptr(abstract_path).to_str().unwrap();
+            CString::new(format!("/real/{}", abstract_path)).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            path: *mut std::os::raw::c_char,
+        ) {
+            drop(CString::from_raw(path));
+        }
+
+        let map_path = sys::LV2_State_Map_Path {
+            handle: std::ptr::null_mut(),
+            abstract_path: Some(abstract_fn),
+            absolute_path: Some(absolute_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        let map_path_feature = unsafe {
+            MapPathFeature::from_feature_ptr(
+                &map_path as *const _ as *const c_void,
+                ThreadingClass::Instantiation,
+            )
+        }
+        .unwrap();
+        let free_path_feature = unsafe {
+            FreePathFeature::from_feature_ptr(
+                &free_path as *const _ as *const c_void,
+                ThreadingClass::Instantiation,
+            )
+        }
+        .unwrap();
+
+        let handle = MapPathHandle::from_features(&map_path_feature, &free_path_feature);
+        let abstract_path = handle
+            .abstract_path(Path::new("/real/presets/a.wav"))
+            .unwrap();
+        assert_eq!("presets/a.wav", abstract_path);
+    }
+
+    #[test]
+    fn test_map_path_invalid_utf8_still_frees() {
+        static FREE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "C" fn absolute_fn(
+            _handle: sys::LV2_State_Map_Path_Handle,
+            _abstract_path: *const std::os::raw::c_char,
+        ) -> *mut std::os::raw::c_char {
+            CString::new(vec![0xFF, 0xFE]).unwrap().into_raw()
+        }
+
+        unsafe extern "C" fn free_fn(
+            _handle: sys::LV2_State_Free_Path_Handle,
+            path: *mut std::os::raw::c_char,
+        ) {
+            FREE_CALLS.fetch_add(1, Ordering::SeqCst);
+            drop(CString::from_raw(path));
+        }
+
+        let map_path = sys::LV2_State_Map_Path {
+            handle: std::ptr::null_mut(),
+            abstract_path: None,
+            absolute_path: Some(absolute_fn),
+        };
+        let free_path = sys::LV2_State_Free_Path {
+            handle: std::ptr::null_mut(),
+            free_path: Some(free_fn),
+        };
+
+        let handle = unsafe { MapPathHandle::new(&map_path, &free_path) };
+        assert!(matches!(handle.absolute_path("x"), Err(StateErr::BadData)));
+        assert_eq!(1, FREE_CALLS.load(Ordering::SeqCst));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,240 @@
+use crate::backend::PropertyBackend;
+use crate::raw::{RawRetrieveHandle, RawStoreHandle, StateFlags};
+use crate::StateErr;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::ffi::c_void;
+use urid::URID;
+
+/// An in-memory property store, useful for driving a plugin's `State::save`/`State::restore`
+/// in tests without a full LV2 host.
+#[derive(Default)]
+pub struct Storage {
+    properties: HashMap<URID, (URID, Vec<u8>)>,
+    flags: HashMap<URID, StateFlags>,
+}
+
+impl Storage {
+    /// Create a [`RawStoreHandle`](crate::raw::RawStoreHandle) that stores properties in this
+    /// `Storage`.
+    pub fn store_handle(&mut self) -> RawStoreHandle {
+        unsafe {
+            RawStoreHandle::new(
+                Some(Self::store_trampoline),
+                self as *mut Self as *mut c_void,
+            )
+        }
+    }
+
+    /// Create a [`RawStoreHandle`](crate::raw::RawStoreHandle) backed by a custom
+    /// [`PropertyBackend`], e.g. [`HeaplessBackend`](crate::backend::HeaplessBackend) to draft
+    /// state properties on a target without a global allocator. Committing still allocates once
+    /// per property regardless of backend; see the [`backend`](crate::backend) module
+    /// documentation.
+    pub fn store_handle_with<B: PropertyBackend>(&mut self) -> RawStoreHandle<B> {
+        unsafe {
+            RawStoreHandle::new(
+                Some(Self::store_trampoline),
+                self as *mut Self as *mut c_void,
+            )
+        }
+    }
+
+    /// Create a [`RawRetrieveHandle`](crate::raw::RawRetrieveHandle) that retrieves properties
+    /// from this `Storage`.
+    pub fn retrieve_handle(&self) -> RawRetrieveHandle {
+        unsafe {
+            RawRetrieveHandle::new(
+                Some(Self::retrieve_trampoline),
+                self as *const Self as *mut c_void,
+            )
+        }
+    }
+
+    /// Iterate over the stored properties as `(key, (type, value))` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&URID, &(URID, Vec<u8>))> {
+        self.properties.iter()
+    }
+
+    /// The `StateFlags` the host's store function was called with for `key` the last time it
+    /// was committed, if any. Useful in tests to assert that flags propagate through
+    /// [`RawStoreHandle`](crate::raw::RawStoreHandle) unchanged.
+    pub fn flags(&self, key: URID) -> Option<StateFlags> {
+        self.flags.get(&key).copied()
+    }
+
+    /// Serialize every stored property into a single byte buffer.
+    ///
+    /// The format is a sequence of records, each a little-endian `(key_urid: u32, type_urid: u32,
+    /// size: u32, data: [u8; size])`, padded after `data` so the next record starts at an 8-byte
+    /// boundary, the same alignment the atom space code already relies on. This gives a
+    /// backend-agnostic snapshot of the store, independent of any LV2 host callbacks, usable by
+    /// test harnesses, preset files, or plugins that checkpoint their own state to disk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (key, (type_, data)) in self.properties.iter() {
+            out.extend_from_slice(&key.get().to_le_bytes());
+            out.extend_from_slice(&type_.get().to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+
+            let padding = (8 - (out.len() % 8)) % 8;
+            out.resize(out.len() + padding, 0);
+        }
+        out
+    }
+
+    /// Deserialize a byte buffer produced by [`to_bytes`](Self::to_bytes) back into a `Storage`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateErr> {
+        let mut properties = HashMap::new();
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            let header = bytes.get(offset..offset + 12).ok_or(StateErr::BadData)?;
+            let key = u32::from_le_bytes(header[0..4].try_into().unwrap());
+            let type_ = u32::from_le_bytes(header[4..8].try_into().unwrap());
+            let size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+            offset += 12;
+
+            let data = bytes
+                .get(offset..offset + size)
+                .ok_or(StateErr::BadData)?
+                .to_vec();
+            offset += size;
+            offset += (8 - (offset % 8)) % 8;
+
+            let key = URID::new(key).ok_or(StateErr::BadData)?;
+            let type_ = URID::new(type_).ok_or(StateErr::BadData)?;
+            properties.insert(key, (type_, data));
+        }
+
+        Ok(Storage {
+            properties,
+            flags: HashMap::new(),
+        })
+    }
+
+    /// Internal trampoline used as the `LV2_State_Store_Function` passed to
+    /// [`RawStoreHandle`](crate::raw::RawStoreHandle).
+    unsafe extern "C" fn store_trampoline(
+        handle: sys::LV2_State_Handle,
+        key: u32,
+        value: *const c_void,
+        size: usize,
+        type_: u32,
+        flags: u32,
+    ) -> sys::LV2_State_Status {
+        let storage = &mut *(handle as *mut Self);
+
+        let key = match URID::new(key) {
+            Some(key) => key,
+            None => return sys::LV2_State_Status_LV2_STATE_ERR_BAD_TYPE,
+        };
+        let type_ = match URID::new(type_) {
+            Some(type_) => type_,
+            None => return sys::LV2_State_Status_LV2_STATE_ERR_BAD_TYPE,
+        };
+
+        let data = std::slice::from_raw_parts(value as *const u8, size).to_vec();
+        storage.properties.insert(key, (type_, data));
+        storage
+            .flags
+            .insert(key, StateFlags::from_bits_truncate(flags));
+
+        sys::LV2_State_Status_LV2_STATE_SUCCESS
+    }
+
+    /// Internal trampoline used as the `LV2_State_Retrieve_Function` passed to
+    /// [`RawRetrieveHandle`](crate::raw::RawRetrieveHandle).
+    unsafe extern "C" fn retrieve_trampoline(
+        handle: sys::LV2_State_Handle,
+        key: u32,
+        size: *mut usize,
+        type_: *mut u32,
+        _flags: *mut u32,
+    ) -> *const c_void {
+        let storage = &*(handle as *const Self);
+
+        let key = match URID::new(key) {
+            Some(key) => key,
+            None => return std::ptr::null(),
+        };
+
+        match storage.properties.get(&key) {
+            Some((property_type, data)) => {
+                *size = data.len();
+                *type_ = property_type.get();
+                data.as_ptr() as *const c_void
+            }
+            None => std::ptr::null(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::StoreHandle;
+    use super::*;
+    use atom::prelude::*;
+    use urid::mapper::*;
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut mapper = Box::pin(HashURIDMapper::new());
+        let interface = mapper.as_mut().make_map_interface();
+        let map = Map::new(&interface);
+        let urids = AtomURIDCache::from_map(&map).unwrap();
+
+        let mut storage = Storage::default();
+        {
+            let mut store_handle = storage.store_handle();
+            store_handle
+                .draft(URID::new(1).unwrap())
+                .init(urids.int, 17)
+                .unwrap();
+            store_handle
+                .draft(URID::new(2).unwrap())
+                .init(urids.float, 1.0)
+                .unwrap();
+            store_handle.commit_all().unwrap();
+        }
+
+        let bytes = storage.to_bytes();
+        let restored = Storage::from_bytes(&bytes).unwrap();
+
+        let mut expected: Vec<_> = storage
+            .iter()
+            .map(|(key, (type_, data))| (*key, *type_, data.clone()))
+            .collect();
+        let mut actual: Vec<_> = restored
+            .iter()
+            .map(|(key, (type_, data))| (*key, *type_, data.clone()))
+            .collect();
+        expected.sort_by_key(|(key, _, _)| key.get());
+        actual.sort_by_key(|(key, _, _)| key.get());
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn captures_non_default_store_flags() {
+        let mut mapper = Box::pin(HashURIDMapper::new());
+        let interface = mapper.as_mut().make_map_interface();
+        let map = Map::new(&interface);
+        let urids = AtomURIDCache::from_map(&map).unwrap();
+
+        let mut storage = Storage::default();
+        let key = URID::new(1).unwrap();
+        {
+            let mut store_handle = storage.store_handle();
+            store_handle
+                .draft_with_flags(key, StateFlags::POD)
+                .unwrap()
+                .init(urids.int, 17)
+                .unwrap();
+            store_handle.commit_all().unwrap();
+        }
+
+        assert_eq!(Some(StateFlags::POD), storage.flags(key));
+    }
+}